@@ -2,13 +2,16 @@
 
 use std::collections::HashMap;
 use std::io::Read;
-use std::sync::{Arc, RwLock};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, RwLock};
 
 use bytes::Bytes;
 use flate2::read::GzDecoder;
 use galileo_mvt::MvtTile;
 use log::error;
-use pmtiles::{DirCacheResult, Directory, DirectoryCache, TileCoord, TileId};
+use lru::LruCache;
+use pmtiles::{Compression, DirCacheResult, Directory, DirectoryCache, TileCoord, TileId};
+use sha2::Digest;
 
 use crate::decoded_image::DecodedImage;
 use crate::error::GalileoError;
@@ -17,7 +20,10 @@ use crate::layer::vector_tile_layer::tile_provider::loader::{TileLoadError, Vect
 use crate::platform::PlatformService;
 use crate::tile_schema::TileIndex;
 
-/// A simple HashMap-based implementation of the `pmtiles::DirectoryCache` trait.
+/// A simple HashMap-based implementation of the `pmtiles::DirectoryCache`
+/// trait. Grows without bound, so prefer [`PmtilesLruDirCache`] for large or
+/// untrusted archives; this type is kept as the default for backward
+/// compatibility.
 #[derive(Default, Clone)]
 pub struct PmtilesDirCache {
     cache: Arc<RwLock<HashMap<usize, Directory>>>,
@@ -48,9 +54,165 @@ impl DirectoryCache for PmtilesDirCache {
     }
 }
 
+/// Rough fixed cost assumed per cached directory entry when estimating a
+/// `Directory`'s in-memory footprint for [`PmtilesLruDirCache`]'s byte
+/// budget. `Directory` doesn't expose its serialized or heap size directly,
+/// so this is a conservative stand-in rather than an exact count.
+const PMTILES_DIR_ENTRY_BYTE_ESTIMATE: usize = 24;
+
+/// Estimates a directory's in-memory footprint from its entry count.
+fn estimate_directory_bytes(directory: &Directory) -> usize {
+    directory.len() * PMTILES_DIR_ENTRY_BYTE_ESTIMATE
+}
+
+struct LruDirCacheState {
+    entries: LruCache<usize, Directory>,
+    bytes_in_use: usize,
+}
+
+/// A bounded implementation of the `pmtiles::DirectoryCache` trait that
+/// evicts the least-recently-used directory once either the configured
+/// directory count or estimated total byte size is exceeded. Use this
+/// instead of [`PmtilesDirCache`] when reading large or untrusted archives
+/// to keep memory use predictable over a long-running session - the byte
+/// budget in particular guards against a hostile archive packing an
+/// arbitrarily large number of entries into a single directory, which a
+/// count-only cap wouldn't bound.
+#[derive(Clone)]
+pub struct PmtilesLruDirCache {
+    state: Arc<Mutex<LruDirCacheState>>,
+    max_bytes: usize,
+}
+
+impl PmtilesLruDirCache {
+    /// Creates a cache that holds at most `capacity` directories, evicting
+    /// the least-recently-used one once full (a `capacity` of 0 is treated
+    /// as 1). Does not bound total byte
+    /// size; use [`PmtilesLruDirCache::with_capacity_and_byte_budget`]
+    /// against untrusted archives where a single directory could be made
+    /// arbitrarily large.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_byte_budget(capacity, usize::MAX)
+    }
+
+    /// Creates a cache that holds at most `capacity` directories and at
+    /// most an estimated `max_bytes` of directory content, evicting the
+    /// least-recently-used directory once either budget is exceeded. A
+    /// `capacity` of 0 is treated as 1.
+    pub fn with_capacity_and_byte_budget(capacity: usize, max_bytes: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            state: Arc::new(Mutex::new(LruDirCacheState {
+                entries: LruCache::new(capacity),
+                bytes_in_use: 0,
+            })),
+            max_bytes,
+        }
+    }
+}
+
+impl DirectoryCache for PmtilesLruDirCache {
+    async fn get_dir_entry(&self, offset: usize, tile_id: TileId) -> DirCacheResult {
+        #[allow(clippy::unwrap_used)]
+        let mut state = self.state.lock().unwrap();
+        if let Some(dir) = state.entries.get(&offset) {
+            if let Some(entry) = dir.find_tile_id(tile_id) {
+                return DirCacheResult::Found(entry.clone());
+            } else {
+                return DirCacheResult::NotFound;
+            }
+        }
+        DirCacheResult::NotCached
+    }
+
+    async fn insert_dir(&self, offset: usize, directory: Directory) {
+        let incoming_bytes = estimate_directory_bytes(&directory);
+
+        #[allow(clippy::unwrap_used)]
+        let mut state = self.state.lock().unwrap();
+        // `push` hands back whatever it displaced - the old directory at the
+        // same offset, or the LRU one evicted by the count cap - so the byte
+        // total stays in step with what the cache actually holds.
+        if let Some((_, displaced)) = state.entries.push(offset, directory) {
+            state.bytes_in_use -= estimate_directory_bytes(&displaced);
+        }
+        state.bytes_in_use += incoming_bytes;
+
+        // The `LruCache`'s own capacity handles eviction for many small
+        // directories; this additionally evicts by recency until we're
+        // back under the byte budget, which is what actually bounds memory
+        // against one (or a few) oversized, hostile directories.
+        while state.bytes_in_use > self.max_bytes {
+            match state.entries.pop_lru() {
+                Some((_, evicted)) => state.bytes_in_use -= estimate_directory_bytes(&evicted),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Self-describing metadata read from a PMTiles archive's header and JSON
+/// metadata block, letting a map auto-configure its initial view instead of
+/// requiring the caller to hardcode zoom range, bounds, and center.
+#[derive(Debug, Clone)]
+pub struct PmtilesMetadata {
+    /// Minimum zoom level present in the archive.
+    pub min_zoom: u8,
+    /// Maximum zoom level present in the archive.
+    pub max_zoom: u8,
+    /// Geographic bounds covered by the archive, in degrees.
+    pub bounds: PmtilesBounds,
+    /// Suggested initial view center.
+    pub center: PmtilesCenter,
+    /// Whether the archive holds raster imagery or vector (MVT) tiles.
+    pub tile_type: pmtiles::TileType,
+    /// Free-form JSON metadata block (layer names, attribution, etc).
+    pub json: serde_json::Value,
+}
+
+/// Geographic bounds, in degrees, as stored in a PMTiles header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PmtilesBounds {
+    /// Western edge longitude.
+    pub west: f64,
+    /// Southern edge latitude.
+    pub south: f64,
+    /// Eastern edge longitude.
+    pub east: f64,
+    /// Northern edge latitude.
+    pub north: f64,
+}
+
+/// Suggested initial view center, as stored in a PMTiles header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PmtilesCenter {
+    /// Center longitude.
+    pub lon: f64,
+    /// Center latitude.
+    pub lat: f64,
+    /// Suggested zoom level for the center.
+    pub zoom: u8,
+}
+
+/// The part of a [`PmtilesTileLoader`] that gets atomically swapped out on
+/// [`PmtilesTileLoader::reload`]: the reader itself plus the bits of header
+/// state we cache from it.
+struct PmtilesReaderState<B, C> {
+    reader: pmtiles::AsyncPmTilesReader<B, C>,
+    tile_compression: Compression,
+}
+
 /// Tile loader for PMTiles format using an async backend (e.g., HTTP)
 pub struct PmtilesTileLoader<B = pmtiles::HttpBackend, C = pmtiles::NoCache> {
-    reader: pmtiles::AsyncPmTilesReader<B, C>,
+    state: Arc<RwLock<Arc<PmtilesReaderState<B, C>>>>,
+}
+
+impl<B, C> Clone for PmtilesTileLoader<B, C> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
 }
 
 impl<B, C> PmtilesTileLoader<B, C>
@@ -60,18 +222,335 @@ where
 {
     /// Creates a new PMTiles tile loader with the given reader
     pub fn new(reader: pmtiles::AsyncPmTilesReader<B, C>) -> Self {
-        Self { reader }
+        Self {
+            state: Arc::new(RwLock::new(Arc::new(Self::reader_state(reader)))),
+        }
+    }
+
+    fn reader_state(reader: pmtiles::AsyncPmTilesReader<B, C>) -> PmtilesReaderState<B, C> {
+        let tile_compression = reader.get_header().tile_compression;
+        PmtilesReaderState {
+            reader,
+            tile_compression,
+        }
+    }
+
+    /// Returns the currently active reader state. Cloning the `Arc` here
+    /// (rather than holding the `RwLock` guard) means a `reload` that runs
+    /// concurrently with an in-flight `load` swaps in a fresh `Arc` without
+    /// disturbing the one the in-flight call already grabbed.
+    fn current_state(&self) -> Arc<PmtilesReaderState<B, C>> {
+        #[allow(clippy::unwrap_used)]
+        self.state.read().unwrap().clone()
+    }
+
+    /// Atomically swaps the active PMTiles reader for `new_reader`, clearing
+    /// the cached header-derived state (tile compression) along with it.
+    /// `load` calls already in flight keep running against the reader they
+    /// started with; only calls made after this returns see `new_reader`.
+    pub async fn reload(&self, new_reader: pmtiles::AsyncPmTilesReader<B, C>) {
+        let new_state = Arc::new(Self::reader_state(new_reader));
+        #[allow(clippy::unwrap_used)]
+        {
+            *self.state.write().unwrap() = new_state;
+        }
     }
 
-    async fn get_tile(&self, index: TileIndex) -> Result<Bytes, GalileoError> {
+    /// Fetches raw tile bytes along with the compression declared by the
+    /// archive active at the time of the call. Both come from the same
+    /// `current_state()` snapshot so a concurrent `reload` can't pair bytes
+    /// from the old archive with the new archive's `tile_compression`.
+    async fn get_tile(&self, index: TileIndex) -> Result<(Bytes, Compression), GalileoError> {
         let coord = TileCoord::new(index.z as u8, index.x as u32, index.y as u32)
             .ok_or(GalileoError::NotFound)?;
 
-        self.reader
+        let state = self.current_state();
+        let bytes = state
+            .reader
             .get_tile(coord)
             .await
             .map_err(|_| GalileoError::NotFound)?
-            .ok_or(GalileoError::NotFound)
+            .ok_or(GalileoError::NotFound)?;
+
+        Ok((bytes, state.tile_compression))
+    }
+
+    /// Reads the archive's self-describing metadata: zoom range, geographic
+    /// bounds, center, tile type, and the free-form JSON metadata block.
+    pub async fn metadata(&self) -> Result<PmtilesMetadata, GalileoError> {
+        let state = self.current_state();
+        let header = state.reader.get_header();
+        let json_str = state.reader.get_metadata().await.map_err(|e| {
+            error!("PMTiles: failed to read metadata: {:?}", e);
+            GalileoError::NotFound
+        })?;
+
+        build_metadata(
+            header.min_zoom,
+            header.max_zoom,
+            PmtilesBounds {
+                west: header.min_longitude,
+                south: header.min_latitude,
+                east: header.max_longitude,
+                north: header.max_latitude,
+            },
+            PmtilesCenter {
+                lon: header.center_longitude,
+                lat: header.center_latitude,
+                zoom: header.center_zoom,
+            },
+            header.tile_type,
+            &json_str,
+        )
+    }
+}
+
+/// Assembles a [`PmtilesMetadata`] from already-extracted header fields
+/// plus the raw JSON metadata block, split out of
+/// [`PmtilesTileLoader::metadata`] so the JSON parsing/field mapping can be
+/// unit tested without needing a real reader.
+fn build_metadata(
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: PmtilesBounds,
+    center: PmtilesCenter,
+    tile_type: pmtiles::TileType,
+    json_str: &str,
+) -> Result<PmtilesMetadata, GalileoError> {
+    let json: serde_json::Value = serde_json::from_str(json_str).map_err(|e| {
+        error!("PMTiles: failed to parse metadata JSON: {:?}", e);
+        GalileoError::NotFound
+    })?;
+
+    Ok(PmtilesMetadata {
+        min_zoom,
+        max_zoom,
+        bounds,
+        center,
+        tile_type,
+        json,
+    })
+}
+
+impl<C> PmtilesTileLoader<pmtiles::HttpBackend, C>
+where
+    C: DirectoryCache + Send + Sync + Default,
+{
+    /// Convenience wrapper around [`PmtilesTileLoader::reload`] that builds
+    /// a fresh HTTP-backed reader for `url` and swaps it in, so a layer can
+    /// pick up an upstream update without being torn down and rebuilt.
+    pub async fn reload_from_url(&self, url: &str) -> Result<(), GalileoError> {
+        let reader = pmtiles::AsyncPmTilesReader::new_with_cached_url(C::default(), url)
+            .await
+            .map_err(|e| {
+                error!("PMTiles: failed to open reader for reload from {url}: {:?}", e);
+                GalileoError::NotFound
+            })?;
+        self.reload(reader).await;
+        Ok(())
+    }
+}
+
+/// A plain (non-mmap) file-backed `pmtiles::AsyncBackend`, used on
+/// platforms or builds where memory-mapping a `.pmtiles` file is
+/// unavailable or undesired.
+pub struct PmtilesFileBackend {
+    file: Arc<std::fs::File>,
+}
+
+impl PmtilesFileBackend {
+    async fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = tokio::task::spawn_blocking(move || std::fs::File::open(path))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok(Self {
+            file: Arc::new(file),
+        })
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+impl pmtiles::AsyncBackend for PmtilesFileBackend {
+    async fn read_exact(&self, offset: usize, length: usize) -> Result<Bytes, pmtiles::PmtError> {
+        // A positioned read doesn't depend on the file's shared cursor on
+        // either platform, so concurrent `load()` calls for
+        // different tiles can't race each other's seeks the way a
+        // clone+seek+read would.
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut buf = vec![0u8; length];
+            read_at(&file, &mut buf, offset as u64)?;
+            Ok::<_, std::io::Error>(buf)
+        })
+        .await
+        .map_err(|_| pmtiles::PmtError::Reading)?
+        .map_err(|_| pmtiles::PmtError::Reading)
+        .map(Bytes::from)
+    }
+}
+
+/// Reads `buf.len()` bytes starting at `offset`, without touching the
+/// file's shared cursor, so it's safe to call concurrently from multiple
+/// clones of the same `Arc<File>`.
+#[cfg(unix)]
+fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+/// Windows equivalent of the Unix `read_at` above: `seek_read` can return
+/// short reads, so loop until `buf` is filled.
+#[cfg(windows)]
+fn read_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    let mut total = 0;
+    while total < buf.len() {
+        let read = file.seek_read(&mut buf[total..], offset + total as u64)?;
+        if read == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "unexpected EOF while reading PMTiles file backend",
+            ));
+        }
+        total += read;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "mmap-async-tokio"))]
+impl<C> PmtilesTileLoader<PmtilesFileBackend, C>
+where
+    C: DirectoryCache + Send + Sync + Default,
+{
+    /// Opens a `.pmtiles` file directly from disk using a plain
+    /// (non-mmap) file backend, with the directory cache wired in
+    /// automatically. Compiled only when the `mmap-async-tokio` feature is
+    /// disabled; with it enabled, `from_path` is the mmap-backed one below.
+    pub async fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self, GalileoError> {
+        let backend = PmtilesFileBackend::open(path).await.map_err(|e| {
+            error!("PMTiles: failed to open file backend: {:?}", e);
+            GalileoError::IO
+        })?;
+        let reader = pmtiles::AsyncPmTilesReader::try_from_cached_source(C::default(), backend)
+            .await
+            .map_err(|e| {
+                error!("PMTiles: failed to read header: {:?}", e);
+                GalileoError::NotFound
+            })?;
+        Ok(Self::new(reader))
+    }
+
+    /// Convenience wrapper around [`PmtilesTileLoader::reload`] that opens
+    /// `path` with a fresh file-backed reader and swaps it in.
+    pub async fn reload_from_path(&self, path: impl AsRef<std::path::Path>) -> Result<(), GalileoError> {
+        let backend = PmtilesFileBackend::open(path).await.map_err(|e| {
+            error!("PMTiles: failed to open file backend for reload: {:?}", e);
+            GalileoError::IO
+        })?;
+        let reader = pmtiles::AsyncPmTilesReader::try_from_cached_source(C::default(), backend)
+            .await
+            .map_err(|e| {
+                error!("PMTiles: failed to read header for reload: {:?}", e);
+                GalileoError::NotFound
+            })?;
+        self.reload(reader).await;
+        Ok(())
+    }
+}
+
+/// Opens a `.pmtiles` file directly from disk using a memory-mapped
+/// backend, which avoids per-tile range-request overhead for archives that
+/// are already available locally. Compiled only when this crate's
+/// `mmap-async-tokio` feature is enabled; it uses the `pmtiles` crate's
+/// mmap backend.
+#[cfg(feature = "mmap-async-tokio")]
+impl<C> PmtilesTileLoader<pmtiles::MmapBackend, C>
+where
+    C: DirectoryCache + Send + Sync + Default,
+{
+    /// Opens `path` with an mmap-backed reader, with the directory cache
+    /// wired in automatically.
+    pub async fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self, GalileoError> {
+        let reader = pmtiles::AsyncPmTilesReader::new_with_cached_path(C::default(), path)
+            .await
+            .map_err(|e| {
+                error!("PMTiles: failed to open mmap backend: {:?}", e);
+                GalileoError::NotFound
+            })?;
+        Ok(Self::new(reader))
+    }
+
+    /// Convenience wrapper around [`PmtilesTileLoader::reload`] that mmaps
+    /// `path` with a fresh reader and swaps it in.
+    pub async fn reload_from_path(&self, path: impl AsRef<std::path::Path>) -> Result<(), GalileoError> {
+        let reader = pmtiles::AsyncPmTilesReader::new_with_cached_path(C::default(), path)
+            .await
+            .map_err(|e| {
+                error!("PMTiles: failed to open mmap backend for reload: {:?}", e);
+                GalileoError::NotFound
+            })?;
+        self.reload(reader).await;
+        Ok(())
+    }
+}
+
+/// Decompresses gzip-encoded bytes into a fresh buffer.
+fn decode_gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoder = GzDecoder::new(bytes);
+    let mut decompressed = Vec::new();
+    decoder.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decompresses a tile payload according to the compression declared in the
+/// PMTiles header, falling back to magic-byte sniffing when the header says
+/// `Unknown`.
+fn decompress_tile(bytes: Bytes, compression: Compression) -> Result<Bytes, TileLoadError> {
+    match compression {
+        Compression::None => Ok(bytes),
+        Compression::Gzip => decode_gzip(&bytes).map(Bytes::from).map_err(|e| {
+            error!("PMTiles: GZIP decompression error: {:?}", e);
+            TileLoadError::Decoding
+        }),
+        Compression::Brotli => {
+            let mut decompressed = Vec::new();
+            brotli::Decompressor::new(&bytes[..], 4096)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| {
+                    error!("PMTiles: Brotli decompression error: {:?}", e);
+                    TileLoadError::Decoding
+                })?;
+            Ok(Bytes::from(decompressed))
+        }
+        Compression::Zstd => {
+            let decompressed = zstd::Decoder::new(&bytes[..])
+                .and_then(|mut decoder| {
+                    let mut out = Vec::new();
+                    decoder.read_to_end(&mut out)?;
+                    Ok(out)
+                })
+                .map_err(|e| {
+                    error!("PMTiles: Zstd decompression error: {:?}", e);
+                    TileLoadError::Decoding
+                })?;
+            Ok(Bytes::from(decompressed))
+        }
+        Compression::Unknown => {
+            // The header doesn't declare a compression scheme: fall back to
+            // sniffing the gzip magic bytes, the only format we've seen in
+            // the wild without a declared `tile_compression`.
+            if bytes.len() > 2 && bytes[0..2] == [0x1F, 0x8B] {
+                decode_gzip(&bytes).map(Bytes::from).map_err(|e| {
+                    error!("PMTiles: GZIP decompression error: {:?}", e);
+                    TileLoadError::Decoding
+                })
+            } else {
+                Ok(bytes)
+            }
+        }
     }
 }
 
@@ -83,7 +562,7 @@ where
     C: DirectoryCache + Send + Sync + maybe_sync::MaybeSend + maybe_sync::MaybeSync,
 {
     async fn load(&self, index: TileIndex) -> Result<DecodedImage, GalileoError> {
-        let bytes = self.get_tile(index).await?;
+        let (bytes, _) = self.get_tile(index).await?;
         crate::platform::instance().decode_image(bytes).await
     }
 }
@@ -96,25 +575,12 @@ where
     C: DirectoryCache + Send + Sync + maybe_sync::MaybeSend + maybe_sync::MaybeSync,
 {
     async fn load(&self, index: TileIndex) -> Result<MvtTile, TileLoadError> {
-        let bytes = self
+        let (bytes, tile_compression) = self
             .get_tile(index)
             .await
             .map_err(|_| TileLoadError::Network)?;
 
-        // Check if this is GZIP compressed data
-        let decompressed_bytes = if bytes.len() > 2 && bytes[0..2] == [0x1F, 0x8B] {
-            // GZIP compressed data - decompress it
-            let mut decoder = GzDecoder::new(&bytes[..]);
-            let mut decompressed = Vec::new();
-            decoder.read_to_end(&mut decompressed).map_err(|e| {
-                error!("PMTiles: GZIP decompression error: {:?}", e);
-                TileLoadError::Decoding
-            })?;
-            Bytes::from(decompressed)
-        } else {
-            // Not compressed, use as-is
-            bytes
-        };
+        let decompressed_bytes = decompress_tile(bytes, tile_compression)?;
 
         MvtTile::decode(decompressed_bytes, false).map_err(|e| {
             error!("PMTiles: Vector tile decoding error: {:?}", e);
@@ -122,3 +588,723 @@ where
         })
     }
 }
+
+/// Largest serialized directory that is allowed to live in the root
+/// directory slot before entries get pushed out into leaf directories, per
+/// the PMTiles spec's "directories should generally be a few KB" guidance.
+const PMTILES_MAX_ROOT_DIR_BYTES: usize = 16_384;
+
+/// Builds a PMTiles archive from in-memory tiles, so Galileo can seed or
+/// export an offline basemap and not just consume one.
+///
+/// Tiles with identical bytes are deduplicated via content hashing, which is
+/// PMTiles' main space-saving feature for things like large runs of empty
+/// ocean tiles.
+pub struct PmtilesTileWriter {
+    tile_type: pmtiles::TileType,
+    tile_compression: Compression,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: PmtilesBounds,
+    center: PmtilesCenter,
+    metadata: serde_json::Value,
+    tiles: std::collections::BTreeMap<u64, Bytes>,
+}
+
+impl PmtilesTileWriter {
+    /// Creates an empty writer for an archive of the given tile type,
+    /// storing tile bytes compressed as `tile_compression` (the writer does
+    /// not compress tiles itself - pass already-compressed bytes to
+    /// [`PmtilesTileWriter::add_tile`]).
+    pub fn new(tile_type: pmtiles::TileType, tile_compression: Compression) -> Self {
+        Self {
+            tile_type,
+            tile_compression,
+            min_zoom: 0,
+            max_zoom: 0,
+            bounds: PmtilesBounds {
+                west: -180.0,
+                south: -90.0,
+                east: 180.0,
+                north: 90.0,
+            },
+            center: PmtilesCenter {
+                lon: 0.0,
+                lat: 0.0,
+                zoom: 0,
+            },
+            metadata: serde_json::json!({}),
+            tiles: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// Sets the zoom range reported in the archive header.
+    pub fn with_zoom_range(mut self, min_zoom: u8, max_zoom: u8) -> Self {
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom;
+        self
+    }
+
+    /// Sets the geographic bounds reported in the archive header.
+    pub fn with_bounds(mut self, bounds: PmtilesBounds) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// Sets the suggested initial view center reported in the archive header.
+    pub fn with_center(mut self, center: PmtilesCenter) -> Self {
+        self.center = center;
+        self
+    }
+
+    /// Sets the free-form JSON metadata block (layer names, attribution).
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Adds (or replaces) a tile's compressed bytes at the given index.
+    pub fn add_tile(&mut self, index: TileIndex, bytes: Bytes) -> Result<(), GalileoError> {
+        let coord = TileCoord::new(index.z as u8, index.x as u32, index.y as u32)
+            .ok_or(GalileoError::NotFound)?;
+        self.tiles.insert(u64::from(TileId::from(coord)), bytes);
+        Ok(())
+    }
+
+    /// Serializes the archive and writes it to `sink`.
+    pub async fn write<W>(&self, sink: &mut W) -> Result<(), GalileoError>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let (tile_data, dir_entries, tile_contents_count) = self.build_tile_data_and_entries();
+        let (root_dir, leaf_dirs) = Self::build_directories(&dir_entries);
+        let metadata_bytes = serde_json::to_vec(&self.metadata)
+            .map_err(|_| GalileoError::Generic("failed to serialize metadata".into()))?;
+
+        let root_dir_offset = PMTILES_HEADER_LEN as u64;
+        let root_dir_length = root_dir.len() as u64;
+        let metadata_offset = root_dir_offset + root_dir_length;
+        let metadata_length = metadata_bytes.len() as u64;
+        let leaf_dirs_offset = metadata_offset + metadata_length;
+        let leaf_dirs_length = leaf_dirs.len() as u64;
+        let tile_data_offset = leaf_dirs_offset + leaf_dirs_length;
+        let tile_data_length = tile_data.len() as u64;
+
+        let header = self.build_header(
+            root_dir_offset,
+            root_dir_length,
+            metadata_offset,
+            metadata_length,
+            leaf_dirs_offset,
+            leaf_dirs_length,
+            tile_data_offset,
+            tile_data_length,
+            dir_entries.len() as u64,
+            tile_contents_count as u64,
+        );
+
+        sink.write_all(&header)
+            .await
+            .map_err(|_| GalileoError::IO)?;
+        sink.write_all(&root_dir).await.map_err(|_| GalileoError::IO)?;
+        sink.write_all(&metadata_bytes)
+            .await
+            .map_err(|_| GalileoError::IO)?;
+        sink.write_all(&leaf_dirs)
+            .await
+            .map_err(|_| GalileoError::IO)?;
+        sink.write_all(&tile_data)
+            .await
+            .map_err(|_| GalileoError::IO)?;
+        sink.flush().await.map_err(|_| GalileoError::IO)?;
+
+        Ok(())
+    }
+
+    /// Deduplicates tiles by content hash and returns the concatenated tile
+    /// data section, the directory entries (sorted in ascending tile id /
+    /// Hilbert curve order, with consecutive tile ids of identical content
+    /// merged into one run-length entry), and the number of distinct tile
+    /// contents after dedup.
+    fn build_tile_data_and_entries(&self) -> (Vec<u8>, Vec<PmtilesDirEntry>, usize) {
+        let mut tile_data = Vec::new();
+        let mut offsets_by_hash: HashMap<[u8; 32], (u64, u32)> = HashMap::new();
+        let mut entries = Vec::with_capacity(self.tiles.len());
+
+        for (&tile_id, bytes) in &self.tiles {
+            let hash = sha2::Sha256::digest(bytes).into();
+            let (offset, length) = *offsets_by_hash.entry(hash).or_insert_with(|| {
+                let offset = tile_data.len() as u64;
+                tile_data.extend_from_slice(bytes);
+                (offset, bytes.len() as u32)
+            });
+
+            entries.push(PmtilesDirEntry {
+                tile_id,
+                run_length: 1,
+                length,
+                offset,
+            });
+        }
+
+        let tile_contents_count = offsets_by_hash.len();
+
+        // Merge consecutive tile ids that share identical content into a
+        // single run-length entry - the format's other big space saving for
+        // contiguous runs of identical tiles (e.g. open ocean).
+        let mut merged: Vec<PmtilesDirEntry> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if let Some(last) = merged.last_mut() {
+                if last.offset == entry.offset
+                    && last.length == entry.length
+                    && last.tile_id + last.run_length as u64 == entry.tile_id
+                {
+                    last.run_length += 1;
+                    continue;
+                }
+            }
+            merged.push(entry);
+        }
+
+        (tile_data, merged, tile_contents_count)
+    }
+
+    /// Splits directory entries into a root directory and, if the root
+    /// would be too large, a set of leaf directories that the root points
+    /// into. Each leaf is itself chunked to stay under the root's own size
+    /// budget (the spec's "a few KB" guidance for individually-fetchable
+    /// directories), recursively halving any chunk that still comes out
+    /// oversized.
+    fn build_directories(entries: &[PmtilesDirEntry]) -> (Vec<u8>, Vec<u8>) {
+        let root_candidate = encode_directory(entries);
+        if root_candidate.len() <= PMTILES_MAX_ROOT_DIR_BYTES {
+            return (root_candidate, Vec::new());
+        }
+
+        // Entries don't fit in a single root directory: chunk them into
+        // leaf directories and have the root hold one pointer entry per
+        // leaf (run_length = 0 marks a leaf pointer, per the spec).
+        let mut leaf_dirs = Vec::new();
+        let mut root_entries = Vec::new();
+
+        for chunk in chunk_entries_to_fit(entries, PMTILES_MAX_ROOT_DIR_BYTES) {
+            let encoded = encode_directory(chunk);
+            root_entries.push(PmtilesDirEntry {
+                tile_id: chunk[0].tile_id,
+                run_length: 0,
+                length: encoded.len() as u32,
+                offset: leaf_dirs.len() as u64,
+            });
+            leaf_dirs.extend_from_slice(&encoded);
+        }
+
+        (encode_directory(&root_entries), leaf_dirs)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_header(
+        &self,
+        root_dir_offset: u64,
+        root_dir_length: u64,
+        metadata_offset: u64,
+        metadata_length: u64,
+        leaf_dirs_offset: u64,
+        leaf_dirs_length: u64,
+        tile_data_offset: u64,
+        tile_data_length: u64,
+        tile_entries_count: u64,
+        tile_contents_count: u64,
+    ) -> Vec<u8> {
+        let mut header = Vec::with_capacity(PMTILES_HEADER_LEN);
+        header.extend_from_slice(b"PMTiles");
+        header.push(3); // spec version
+
+        for value in [
+            root_dir_offset,
+            root_dir_length,
+            metadata_offset,
+            metadata_length,
+            leaf_dirs_offset,
+            leaf_dirs_length,
+            tile_data_offset,
+            tile_data_length,
+            self.tiles.len() as u64, // addressed_tiles_count
+            tile_entries_count,      // tile_entries_count
+            tile_contents_count,     // tile_contents_count: distinct contents after dedup
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+
+        header.push(1); // clustered: tiles are written in tile id order
+        header.push(Compression::None as u8); // internal_compression (directories/metadata stored raw)
+        header.push(self.tile_compression as u8);
+        header.push(self.tile_type as u8);
+        header.push(self.min_zoom);
+        header.push(self.max_zoom);
+        header.extend_from_slice(&((self.bounds.west * 1e7) as i32).to_le_bytes());
+        header.extend_from_slice(&((self.bounds.south * 1e7) as i32).to_le_bytes());
+        header.extend_from_slice(&((self.bounds.east * 1e7) as i32).to_le_bytes());
+        header.extend_from_slice(&((self.bounds.north * 1e7) as i32).to_le_bytes());
+        header.push(self.center.zoom);
+        header.extend_from_slice(&((self.center.lon * 1e7) as i32).to_le_bytes());
+        header.extend_from_slice(&((self.center.lat * 1e7) as i32).to_le_bytes());
+
+        header
+    }
+}
+
+/// Fixed byte length of a PMTiles v3 header.
+const PMTILES_HEADER_LEN: usize = 127;
+
+/// A single directory entry: a tile id (or, with `run_length == 0`, a
+/// pointer to a leaf directory), how many consecutive ids share its bytes,
+/// and where those bytes live.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PmtilesDirEntry {
+    tile_id: u64,
+    run_length: u32,
+    length: u32,
+    offset: u64,
+}
+
+/// Encodes directory entries following the PMTiles spec layout: entry
+/// count, then delta-encoded tile ids, run lengths, lengths, and offsets
+/// (each as a dedicated varint column, with offsets that are contiguous
+/// with the previous entry collapsed to `0`).
+fn encode_directory(entries: &[PmtilesDirEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, entries.len() as u64);
+
+    let mut prev_id = 0u64;
+    for entry in entries {
+        write_varint(&mut out, entry.tile_id - prev_id);
+        prev_id = entry.tile_id;
+    }
+    for entry in entries {
+        write_varint(&mut out, entry.run_length as u64);
+    }
+    for entry in entries {
+        write_varint(&mut out, entry.length as u64);
+    }
+    let mut prev_end = None;
+    for entry in entries {
+        if Some(entry.offset) == prev_end {
+            write_varint(&mut out, 0);
+        } else {
+            write_varint(&mut out, entry.offset + 1);
+        }
+        prev_end = Some(entry.offset + entry.length as u64);
+    }
+
+    out
+}
+
+/// Splits `entries` into chunks whose encoded directory size each stays
+/// within `max_bytes`, by recursively halving any chunk that still comes
+/// out oversized; the number of chunks is not necessarily the minimum. A single-entry chunk is returned as-is even if it
+/// exceeds `max_bytes` - there's nothing smaller to split it into.
+fn chunk_entries_to_fit(
+    entries: &[PmtilesDirEntry],
+    max_bytes: usize,
+) -> Vec<&[PmtilesDirEntry]> {
+    if entries.len() <= 1 || encode_directory(entries).len() <= max_bytes {
+        return vec![entries];
+    }
+
+    let mid = entries.len() / 2;
+    let mut chunks = chunk_entries_to_fit(&entries[..mid], max_bytes);
+    chunks.extend(chunk_entries_to_fit(&entries[mid..], max_bytes));
+    chunks
+}
+
+/// Writes `value` as a little-endian base-128 varint.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_tile_dispatches_by_declared_compression() {
+        use std::io::Write;
+
+        let original = b"hello pmtiles".to_vec();
+
+        assert_eq!(
+            decompress_tile(Bytes::from(original.clone()), Compression::None).unwrap(),
+            Bytes::from(original.clone())
+        );
+
+        let mut gzip_bytes = Vec::new();
+        {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut gzip_bytes, flate2::Compression::default());
+            encoder.write_all(&original).unwrap();
+            encoder.finish().unwrap();
+        }
+        assert_eq!(
+            decompress_tile(Bytes::from(gzip_bytes.clone()), Compression::Gzip).unwrap(),
+            Bytes::from(original.clone())
+        );
+
+        let mut brotli_bytes = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut brotli_bytes, 4096, 5, 22);
+            encoder.write_all(&original).unwrap();
+        }
+        assert_eq!(
+            decompress_tile(Bytes::from(brotli_bytes), Compression::Brotli).unwrap(),
+            Bytes::from(original.clone())
+        );
+
+        let zstd_bytes = zstd::encode_all(&original[..], 0).unwrap();
+        assert_eq!(
+            decompress_tile(Bytes::from(zstd_bytes), Compression::Zstd).unwrap(),
+            Bytes::from(original.clone())
+        );
+
+        // Unknown falls back to gzip magic-byte sniffing.
+        assert_eq!(
+            decompress_tile(Bytes::from(gzip_bytes), Compression::Unknown).unwrap(),
+            Bytes::from(original.clone())
+        );
+
+        // Unknown with no gzip magic bytes is passed through untouched.
+        assert_eq!(
+            decompress_tile(Bytes::from(original.clone()), Compression::Unknown).unwrap(),
+            Bytes::from(original)
+        );
+    }
+
+    #[test]
+    fn build_metadata_maps_header_fields_and_parses_json() {
+        let bounds = PmtilesBounds {
+            west: -10.0,
+            south: -5.0,
+            east: 10.0,
+            north: 5.0,
+        };
+        let center = PmtilesCenter {
+            lon: 1.0,
+            lat: 2.0,
+            zoom: 4,
+        };
+
+        let metadata = build_metadata(
+            0,
+            14,
+            bounds,
+            center,
+            pmtiles::TileType::Mvt,
+            r#"{"name":"test layer"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.min_zoom, 0);
+        assert_eq!(metadata.max_zoom, 14);
+        assert_eq!(metadata.bounds, bounds);
+        assert_eq!(metadata.center, center);
+        assert_eq!(metadata.json, serde_json::json!({"name": "test layer"}));
+    }
+
+    #[test]
+    fn build_metadata_rejects_invalid_json() {
+        let bounds = PmtilesBounds {
+            west: 0.0,
+            south: 0.0,
+            east: 0.0,
+            north: 0.0,
+        };
+        let center = PmtilesCenter {
+            lon: 0.0,
+            lat: 0.0,
+            zoom: 0,
+        };
+
+        assert!(build_metadata(0, 0, bounds, center, pmtiles::TileType::Mvt, "not json").is_err());
+    }
+
+    fn read_varint(buf: &mut &[u8]) -> u64 {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = buf[0];
+            *buf = &buf[1..];
+            result |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        result
+    }
+
+    /// Decodes what `encode_directory` produced, for round-trip testing.
+    /// `pmtiles::Directory` parses this encoding too (see `test_directory`),
+    /// but this returns the raw entries for a field-by-field comparison. It
+    /// mirrors the encoder's column layout deliberately rather than reusing
+    /// its code.
+    fn decode_directory(bytes: &[u8]) -> Vec<PmtilesDirEntry> {
+        let mut buf = bytes;
+        let count = read_varint(&mut buf) as usize;
+
+        let mut tile_ids = Vec::with_capacity(count);
+        let mut prev_id = 0u64;
+        for _ in 0..count {
+            prev_id += read_varint(&mut buf);
+            tile_ids.push(prev_id);
+        }
+
+        let run_lengths: Vec<u32> = (0..count).map(|_| read_varint(&mut buf) as u32).collect();
+        let lengths: Vec<u32> = (0..count).map(|_| read_varint(&mut buf) as u32).collect();
+
+        let mut entries = Vec::with_capacity(count);
+        let mut prev_end: Option<u64> = None;
+        for i in 0..count {
+            let raw = read_varint(&mut buf);
+            let offset = if raw == 0 {
+                prev_end.expect("contiguous offset marker with no previous entry")
+            } else {
+                raw - 1
+            };
+            prev_end = Some(offset + lengths[i] as u64);
+            entries.push(PmtilesDirEntry {
+                tile_id: tile_ids[i],
+                run_length: run_lengths[i],
+                length: lengths[i],
+                offset,
+            });
+        }
+
+        entries
+    }
+
+    #[test]
+    fn varint_roundtrips() {
+        for value in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            let mut cursor = buf.as_slice();
+            assert_eq!(read_varint(&mut cursor), value);
+            assert!(cursor.is_empty());
+        }
+    }
+
+    #[test]
+    fn directory_roundtrips() {
+        let entries = vec![
+            PmtilesDirEntry {
+                tile_id: 0,
+                run_length: 1,
+                length: 10,
+                offset: 0,
+            },
+            PmtilesDirEntry {
+                tile_id: 1,
+                run_length: 3,
+                length: 20,
+                offset: 10,
+            },
+            PmtilesDirEntry {
+                tile_id: 9,
+                run_length: 1,
+                length: 5,
+                offset: 50,
+            },
+        ];
+
+        let encoded = encode_directory(&entries);
+        assert_eq!(decode_directory(&encoded), entries);
+    }
+
+    #[test]
+    fn chunk_entries_to_fit_stays_under_budget() {
+        let entries: Vec<_> = (0..2_000u64)
+            .map(|i| PmtilesDirEntry {
+                tile_id: i,
+                run_length: 1,
+                length: 100,
+                offset: i * 100,
+            })
+            .collect();
+
+        let budget = 256;
+        let chunks = chunk_entries_to_fit(&entries, budget);
+
+        let total: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+        assert_eq!(total, entries.len());
+        for chunk in chunks {
+            assert!(
+                chunk.len() == 1 || encode_directory(chunk).len() <= budget,
+                "chunk of {} entries encoded to {} bytes, over the {budget} byte budget",
+                chunk.len(),
+                encode_directory(chunk).len()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn writer_reports_deduped_tile_contents_count() {
+        let mut writer = PmtilesTileWriter::new(pmtiles::TileType::Mvt, Compression::None);
+        writer.tiles.insert(0, Bytes::from_static(b"abc"));
+        writer.tiles.insert(1, Bytes::from_static(b"abc")); // duplicate content
+        writer.tiles.insert(2, Bytes::from_static(b"def"));
+
+        let mut out = Vec::new();
+        writer.write(&mut out).await.unwrap();
+
+        assert_eq!(&out[0..7], b"PMTiles");
+
+        let addressed_tiles_count = u64::from_le_bytes(out[72..80].try_into().unwrap());
+        let tile_contents_count = u64::from_le_bytes(out[88..96].try_into().unwrap());
+        assert_eq!(addressed_tiles_count, 3);
+        assert_eq!(tile_contents_count, 2);
+    }
+
+    /// Builds a `Directory` with a single entry for `tile_id`, by encoding
+    /// it with our own `encode_directory` and parsing it back - there's no
+    /// lighter-weight way to construct one, since `Directory` only exposes
+    /// itself via parsing.
+    fn test_directory(tile_id: u64) -> Directory {
+        let entries = [PmtilesDirEntry {
+            tile_id,
+            run_length: 1,
+            length: 1,
+            offset: 0,
+        }];
+        Directory::try_from(Bytes::from(encode_directory(&entries)))
+            .expect("a directory we just encoded ourselves should parse back")
+    }
+
+    fn tile_id_for(z: u8, x: u32, y: u32) -> TileId {
+        TileId::from(TileCoord::new(z, x, y).unwrap())
+    }
+
+    #[tokio::test]
+    async fn lru_dir_cache_evicts_least_recently_used_by_count() {
+        let cache = PmtilesLruDirCache::with_capacity(2);
+
+        cache.insert_dir(0, test_directory(0)).await;
+        cache.insert_dir(1, test_directory(1)).await;
+        // Touch offset 0 so offset 1 becomes the least-recently-used entry.
+        let _ = cache.get_dir_entry(0, tile_id_for(0, 0, 0)).await;
+        // Capacity is 2, so this must evict offset 1, not offset 0.
+        cache.insert_dir(2, test_directory(2)).await;
+
+        assert!(matches!(
+            cache.get_dir_entry(1, tile_id_for(0, 0, 0)).await,
+            DirCacheResult::NotCached
+        ));
+        assert!(!matches!(
+            cache.get_dir_entry(0, tile_id_for(0, 0, 0)).await,
+            DirCacheResult::NotCached
+        ));
+        assert!(!matches!(
+            cache.get_dir_entry(2, tile_id_for(0, 0, 0)).await,
+            DirCacheResult::NotCached
+        ));
+    }
+
+    #[tokio::test]
+    async fn lru_dir_cache_evicts_by_byte_budget_even_under_count_cap() {
+        let small = test_directory(0);
+        let budget = estimate_directory_bytes(&small) * 2;
+        let cache = PmtilesLruDirCache::with_capacity_and_byte_budget(10, budget);
+
+        cache.insert_dir(0, small).await;
+        // A directory with many more entries blows the byte budget on its
+        // own even though the count cap (10) is nowhere near hit - this is
+        // exactly the hostile-single-directory case a count-only cap can't
+        // bound.
+        let many_entries: Vec<_> = (0..64)
+            .map(|i| PmtilesDirEntry {
+                tile_id: i,
+                run_length: 1,
+                length: 1,
+                offset: 0,
+            })
+            .collect();
+        let big = Directory::try_from(Bytes::from(encode_directory(&many_entries)))
+            .expect("a directory we just encoded ourselves should parse back");
+        cache.insert_dir(1, big).await;
+
+        assert!(matches!(
+            cache.get_dir_entry(0, tile_id_for(0, 0, 0)).await,
+            DirCacheResult::NotCached
+        ));
+    }
+
+    /// In-memory `AsyncBackend` so reload tests don't need a real file or
+    /// network access: it just serves byte ranges out of a `Bytes` buffer.
+    struct InMemoryBackend {
+        bytes: Bytes,
+    }
+
+    #[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+    #[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+    impl pmtiles::AsyncBackend for InMemoryBackend {
+        async fn read_exact(&self, offset: usize, length: usize) -> Result<Bytes, pmtiles::PmtError> {
+            self.bytes
+                .get(offset..offset + length)
+                .map(|slice| Bytes::copy_from_slice(slice))
+                .ok_or(pmtiles::PmtError::Reading)
+        }
+    }
+
+    /// Builds a tiny valid archive declaring `tile_compression` and wraps
+    /// it in a reader, by round-tripping through `PmtilesTileWriter` -
+    /// reusing the writer this way exercises it as a real producer of
+    /// readable archives, not just a header-bytes assertion.
+    async fn build_test_reader(
+        tile_compression: Compression,
+    ) -> pmtiles::AsyncPmTilesReader<InMemoryBackend, pmtiles::NoCache> {
+        let mut writer = PmtilesTileWriter::new(pmtiles::TileType::Mvt, tile_compression)
+            .with_zoom_range(0, 0);
+        writer.tiles.insert(0, Bytes::from_static(b"tile"));
+
+        let mut archive = Vec::new();
+        writer.write(&mut archive).await.unwrap();
+
+        pmtiles::AsyncPmTilesReader::try_from_cached_source(
+            pmtiles::NoCache::default(),
+            InMemoryBackend {
+                bytes: Bytes::from(archive),
+            },
+        )
+        .await
+        .expect("writer output should parse back as a valid archive")
+    }
+
+    #[tokio::test]
+    async fn reload_swaps_reader_and_compression_as_one_unit() {
+        let reader_gzip = build_test_reader(Compression::Gzip).await;
+        let reader_none = build_test_reader(Compression::None).await;
+
+        let loader = PmtilesTileLoader::new(reader_gzip);
+        assert_eq!(loader.current_state().tile_compression, Compression::Gzip);
+
+        // A snapshot taken before `reload` must keep reporting the old
+        // compression even after `reload` returns - this is the guarantee
+        // `get_tile` relies on to pair bytes and compression from the same
+        // archive instead of splitting them across a concurrent reload.
+        let snapshot_before_reload = loader.current_state();
+        loader.reload(reader_none).await;
+
+        assert_eq!(snapshot_before_reload.tile_compression, Compression::Gzip);
+        assert_eq!(loader.current_state().tile_compression, Compression::None);
+    }
+}